@@ -0,0 +1,128 @@
+//! OAuth2 access tokens for talking to BigTable.
+//!
+//! Tokens are refreshed proactively on a background task once they enter a staleness window,
+//! rather than being awaited inline on the request hot path. `AccessToken::get` always returns
+//! whatever token is currently cached ([`AccessToken::new`] fetches the first one before
+//! returning); callers that would rather block than use a token inside the staleness window
+//! should await [`AccessToken::refresh`] directly.
+
+use gcp_auth::AuthenticationManager;
+use log::error;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// How long before a cached token's actual expiry we treat it as stale and kick off a
+/// background refresh, so in-flight requests never observe an expired token.
+const STALENESS_WINDOW: Duration = Duration::from_secs(60);
+
+/// GCP access tokens are conventionally valid for an hour; `gcp_auth`'s `Token` doesn't expose
+/// its exact expiry, so this is used as a conservative estimate for when we should next refresh.
+const TOKEN_LIFETIME: Duration = Duration::from_secs(3600);
+
+#[derive(Clone, Copy, Debug)]
+pub enum Scope {
+    BigTableData,
+    BigTableDataReadOnly,
+}
+
+impl Scope {
+    fn as_str(self) -> &'static str {
+        match self {
+            Scope::BigTableData => "https://www.googleapis.com/auth/bigtable.data",
+            Scope::BigTableDataReadOnly => "https://www.googleapis.com/auth/bigtable.data.readonly",
+        }
+    }
+}
+
+struct Token {
+    value: String,
+    expires_at: Instant,
+}
+
+#[derive(Clone)]
+pub struct AccessToken {
+    scope: Scope,
+    token: Arc<RwLock<Token>>,
+    // Serializes refreshes so that a `refresh()` call made while a background refresh (or
+    // another `refresh()` call) is already in flight waits on it instead of returning early with
+    // a stale token.
+    refresh_lock: Arc<Mutex<()>>,
+}
+
+impl AccessToken {
+    pub async fn new(scope: Scope) -> Result<Self, String> {
+        let token = fetch_token(scope).await?;
+        Ok(Self {
+            scope,
+            token: Arc::new(RwLock::new(token)),
+            refresh_lock: Arc::new(Mutex::new(())),
+        })
+    }
+
+    /// Return the currently cached token without awaiting a network round-trip. If the cached
+    /// token is within `STALENESS_WINDOW` of expiring, a background refresh is kicked off (unless
+    /// one is already in flight) so that a *subsequent* call sees a fresh token; this call still
+    /// returns the (possibly slightly stale, but not yet expired) token immediately.
+    pub fn get(&self) -> String {
+        self.maybe_spawn_refresh();
+        self.token.read().expect("token lock poisoned").value.clone()
+    }
+
+    /// If the cached token is within `STALENESS_WINDOW` of expiring, fetch and cache a new one,
+    /// blocking the caller until that completes; a token that is not stale is left as it is. For
+    /// callers that would rather wait than risk an expired token. If another refresh (foreground
+    /// or background) is already in flight, this waits for it to finish and then re-checks
+    /// staleness, rather than returning early with a possibly stale token.
+    pub async fn refresh(&self) {
+        let _guard = self.refresh_lock.lock().await;
+        if self.is_stale() {
+            self.do_refresh().await;
+        }
+    }
+
+    fn is_stale(&self) -> bool {
+        Instant::now() + STALENESS_WINDOW
+            >= self.token.read().expect("token lock poisoned").expires_at
+    }
+
+    fn maybe_spawn_refresh(&self) {
+        if !self.is_stale() {
+            return;
+        }
+        let guard = match Arc::clone(&self.refresh_lock).try_lock_owned() {
+            Ok(guard) => guard,
+            Err(_) => return, // a refresh is already in flight
+        };
+
+        let this = self.clone();
+        tokio::spawn(async move {
+            this.do_refresh().await;
+            drop(guard);
+        });
+    }
+
+    async fn do_refresh(&self) {
+        match fetch_token(self.scope).await {
+            Ok(token) => *self.token.write().expect("token lock poisoned") = token,
+            Err(err) => error!("Failed to refresh BigTable access token: {}", err),
+        }
+    }
+}
+
+/// Mint a fresh token for `scope` via `gcp_auth`, which in turn tries, in order, a service
+/// account key pointed to by `GOOGLE_APPLICATION_CREDENTIALS`, the `gcloud` CLI's own cached
+/// credentials, and the GCE/GKE metadata server.
+async fn fetch_token(scope: Scope) -> Result<Token, String> {
+    let authentication_manager = AuthenticationManager::new()
+        .await
+        .map_err(|err| format!("failed to initialize GCP authentication: {}", err))?;
+    let token = authentication_manager
+        .get_token(&[scope.as_str()])
+        .await
+        .map_err(|err| format!("failed to fetch an access token: {}", err))?;
+    Ok(Token {
+        value: token.as_str().to_string(),
+        expires_at: Instant::now() + TOKEN_LIFETIME,
+    })
+}
@@ -1,20 +1,22 @@
 use crate::google::bigtable::v2::{
-    bigtable_client::BigtableClient, read_rows_response::cell_chunk::RowStatus, ReadRowsRequest,
-    ReadRowsResponse,
+    bigtable_client::BigtableClient, mutate_rows_request::Entry, mutation::Mutation as MutationKind,
+    mutation::SetCell, read_rows_response::cell_chunk::RowStatus, read_rows_response::CellChunk,
+    row_range, Mutation, MutateRowRequest, MutateRowsRequest, ReadRowsRequest, ReadRowsResponse,
+    RowRange,
 };
 
 use crate::{
     access_token::{AccessToken, Scope},
+    compression::{compress, compress_best, decompress, CompressionMethod},
     root_ca_certificate,
 };
+use async_stream::try_stream;
+use futures::{pin_mut, Stream, StreamExt};
 use log::{info, trace, warn};
-use std::time::{Duration, Instant};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use thiserror::Error;
 use tonic::transport::Endpoint;
-use tonic::{
-    codec::Streaming, metadata::MetadataValue, transport::Channel, transport::ClientTlsConfig,
-    Request,
-};
+use tonic::{metadata::MetadataValue, transport::Channel, transport::ClientTlsConfig, Request};
 
 pub type RowKey = Vec<u8>;
 pub type RowData = Vec<(CellName, CellValue)>;
@@ -80,12 +82,115 @@ impl std::convert::From<tonic::Status> for Error {
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Default value size, in bytes, at or above which [`CompressionPolicy::enabled`] compresses a
+/// cell value before writing it.
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 4 * 1024;
+
+/// tonic's own default inbound (decoding) message limit. `BigTableConnection::new` applies
+/// whatever `max_message_size` it is given as both the decoding and the encoding limit, so
+/// passing this value also caps outgoing messages at 4 MiB. Rows containing multi-megabyte cells
+/// need a higher ceiling to avoid failing mid-stream.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 4 * 1024 * 1024;
+
+/// BigTable rejects any single cell larger than this. Values over the limit must be split
+/// across multiple qualifiers; see [`BigTable::write_row_checked`] and
+/// [`BigTable::write_rows_checked`].
+pub const CELL_SIZE_LIMIT: usize = 10 * 1024 * 1024;
+
+/// Protobuf/field overhead budgeted per chunk in addition to its qualifier and value, so a
+/// chunk's actual on-the-wire size stays safely under [`CELL_SIZE_LIMIT`]. Chunks are compressed
+/// after splitting; the budget covers the method tag byte, but a
+/// [`CompressionPolicy::forced_method`] codec that expands incompressible data by more than this
+/// can still push a chunk over the limit.
+const CHUNK_OVERHEAD: usize = 64;
+
+/// Reserved first byte of a qualifier produced by [`chunk_cell_value`] when a cell value needs
+/// splitting. [`chunk_cell_value`] rejects any qualifier that already begins with this byte, so
+/// `chunk_base_name` can recognize a chunk piece by this marker alone instead of guessing from
+/// the rest of the qualifier's bytes, which could otherwise collide with an ordinary, unchunked
+/// qualifier and silently merge it into an unrelated cell.
+const CHUNK_MARKER: u8 = 0x01;
+
+/// Every chunk piece's qualifier is `[CHUNK_MARKER] ++ base_name.len() as 4-byte big-endian ++
+/// base_name ++ chunk_index as 4-byte big-endian`, i.e. [`CHUNK_MARKER`] plus this many bytes of
+/// fixed overhead around the base qualifier. Encoding the base qualifier's length explicitly
+/// (rather than relying on a separator byte within a variable-length suffix) means
+/// `chunk_base_name` never has to guess where the index begins, and -- since the encoded prefix
+/// `[CHUNK_MARKER] ++ len ++ base_name` is identical across every piece of the same cell -- two
+/// pieces compare equal up to that prefix and are then ordered purely by the big-endian index
+/// that follows, so `piece(0) < piece(1) < piece(2) ...` exactly matches write order, which is
+/// what a scan returns.
+const CHUNK_QUALIFIER_OVERHEAD: usize = 1 + 4 + 4;
+
+/// Controls whether and how cell values are transparently compressed on write.
+///
+/// Every value this client writes is tagged with a leading method byte (see
+/// [`crate::compression`]), including uncompressed ones and ones left untouched because `enabled`
+/// is `false` or they're under `threshold` -- writing always tags, unconditionally. So that the
+/// default policy round-trips losslessly out of the box, `tagged_reads` defaults to `true` as
+/// well. A table that predates this policy (or that other, non-tagging clients still write to)
+/// may contain raw, untagged values whose first byte would be misread as a method tag; if this
+/// table has any such values, set `tagged_reads: false` explicitly until every writer has been
+/// migrated to tag its values, to avoid corrupting them on read.
+#[derive(Clone, Copy, Debug)]
+pub struct CompressionPolicy {
+    /// When `false`, values are written uncompressed (tagged with `CompressionMethod::None`).
+    pub enabled: bool,
+    /// Values smaller than this are written uncompressed even when `enabled` is `true`.
+    pub threshold: usize,
+    /// When set, always use this codec instead of picking the smallest of all candidates.
+    pub forced_method: Option<CompressionMethod>,
+    /// When `true`, every stored value is assumed to carry a leading method tag and is passed
+    /// through [`decompress`](crate::compression::decompress) on read. Set this to `false` only
+    /// for a table that contains values written before tagging was introduced; otherwise an
+    /// untagged value's leading byte is misinterpreted as a method tag and the value is corrupted.
+    pub tagged_reads: bool,
+}
+
+impl Default for CompressionPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            threshold: DEFAULT_COMPRESSION_THRESHOLD,
+            forced_method: None,
+            tagged_reads: true,
+        }
+    }
+}
+
+/// Controls retrying of transient RPC failures with exponential backoff.
+///
+/// Only `tonic::Code::{Unavailable, DeadlineExceeded, ResourceExhausted, Aborted, Internal}` are
+/// treated as transient; anything else (e.g. `NotFound`, `PermissionDenied`) is returned to the
+/// caller immediately since retrying it would never succeed.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    pub max_retries: usize,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(5),
+            multiplier: 2.0,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct BigTableConnection {
     access_token: Option<AccessToken>,
     channel: tonic::transport::Channel,
     table_prefix: String,
     timeout: Option<Duration>,
+    compression_policy: CompressionPolicy,
+    retry_policy: RetryPolicy,
+    max_message_size: usize,
 }
 
 impl BigTableConnection {
@@ -97,12 +202,16 @@ impl BigTableConnection {
     ///
     /// The BIGTABLE_EMULATOR_HOST environment variable is also respected.
     ///
+    /// `max_message_size` sets the inbound/outbound gRPC message size limit; pass
+    /// [`DEFAULT_MAX_MESSAGE_SIZE`] unless reading or writing cells larger than that.
+    ///
     pub async fn new(
         project_id: &str,
         instance_name: &str,
         read_only: bool,
         channel_size: usize,
         timeout: Option<Duration>,
+        max_message_size: usize,
     ) -> Result<Self> {
         match std::env::var("BIGTABLE_EMULATOR_HOST") {
             Ok(endpoint) => {
@@ -128,6 +237,9 @@ impl BigTableConnection {
                     channel: Channel::balance_list(endpoints.into_iter()),
                     table_prefix: format!("projects/emulator/instances/{}/tables/", instance_name),
                     timeout,
+                    compression_policy: CompressionPolicy::default(),
+                    retry_policy: RetryPolicy::default(),
+                    max_message_size,
                 })
             }
 
@@ -179,11 +291,26 @@ impl BigTableConnection {
                     channel: Channel::balance_list(endpoints.into_iter()),
                     table_prefix,
                     timeout,
+                    compression_policy: CompressionPolicy::default(),
+                    retry_policy: RetryPolicy::default(),
+                    max_message_size,
                 })
             }
         }
     }
 
+    /// Override the default [`CompressionPolicy`]. Besides controlling what gets compressed on
+    /// write, `policy.tagged_reads` also controls whether reads attempt decompression at all;
+    /// see [`CompressionPolicy`] for when a table needs to opt back out of it.
+    pub fn set_compression_policy(&mut self, policy: CompressionPolicy) {
+        self.compression_policy = policy;
+    }
+
+    /// Override the default [`RetryPolicy`] used to retry transient RPC failures.
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry_policy = policy;
+    }
+
     /// Create a new BigTable client.
     ///
     /// Clients require `&mut self`, due to `Tonic::transport::Channel` limitations, however
@@ -206,11 +333,16 @@ impl BigTableConnection {
         } else {
             BigtableClient::new(self.channel.clone())
         };
+        let client = client
+            .max_decoding_message_size(self.max_message_size)
+            .max_encoding_message_size(self.max_message_size);
         BigTable {
             access_token: self.access_token.clone(),
             client,
             table_prefix: self.table_prefix.clone(),
             timeout: self.timeout,
+            compression_policy: self.compression_policy,
+            retry_policy: self.retry_policy,
         }
     }
 }
@@ -220,96 +352,772 @@ pub struct BigTable {
     client: BigtableClient<tonic::transport::Channel>,
     pub table_prefix: String,
     timeout: Option<Duration>,
+    compression_policy: CompressionPolicy,
+    retry_policy: RetryPolicy,
 }
 
 impl BigTable {
+    /// Read rows matching `request`, transparently retrying transient failures according to
+    /// `self.retry_policy`.
+    ///
+    /// `self.timeout` is the overall deadline across every attempt, not a per-attempt timeout: a
+    /// retry picks up where the previous attempt left off rather than restarting the clock.
+    /// Since `ReadRowsResponse` is a stream, a retry cannot simply resume a half-read response;
+    /// instead the already-committed rows are kept and the request is narrowed to start just
+    /// after the last row committed so far, so the server never resends rows the caller has
+    /// already decoded. Built on top of [`BigTable::read_rows_stream`] by collecting it.
     pub async fn read_rows(&mut self, request: ReadRowsRequest) -> Result<Vec<(RowKey, RowData)>> {
-        self.refresh_access_token().await;
-        let response = self.client.read_rows(request).await?.into_inner();
-        self.decode_read_rows_response(response).await
-    }
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let mut rows: Vec<(RowKey, RowData)> = vec![];
+        let mut last_committed_row_key: Option<RowKey> = None;
+        let mut request = request;
+        let mut backoff = self.retry_policy.initial_backoff;
+        let mut attempt = 0;
+
+        loop {
+            let mut result = Ok(());
+            {
+                let stream = self.read_rows_stream_with_deadline(request.clone(), deadline);
+                pin_mut!(stream);
+                while let Some(item) = stream.next().await {
+                    match item {
+                        Ok((row_key, row_data)) => {
+                            last_committed_row_key = Some(row_key.clone());
+                            rows.push((row_key, row_data));
+                        }
+                        Err(err) => {
+                            result = Err(err);
+                            break;
+                        }
+                    }
+                }
+            }
 
-    async fn refresh_access_token(&self) {
-        if let Some(ref access_token) = self.access_token {
-            access_token.refresh().await;
+            match result {
+                Ok(()) => return Ok(rows),
+                Err(err) if attempt < self.retry_policy.max_retries && is_retryable(&err) => {
+                    attempt += 1;
+                    warn!(
+                        "read_rows attempt {} failed with a transient error, retrying in {:?}: {}",
+                        attempt, backoff, err
+                    );
+                    if let Some(row_key) = &last_committed_row_key {
+                        match start_after_row_key(request, row_key) {
+                            Some(narrowed) => request = narrowed,
+                            // Every row in the requested set has already been committed.
+                            None => return Ok(rows),
+                        }
+                    }
+                    tokio::time::sleep(jittered(backoff)).await;
+                    backoff = next_backoff(backoff, &self.retry_policy);
+                }
+                Err(err) => return Err(err),
+            }
         }
     }
 
-    async fn decode_read_rows_response(
-        &self,
-        mut rrr: Streaming<ReadRowsResponse>,
-    ) -> Result<Vec<(RowKey, RowData)>> {
-        let mut rows: Vec<(RowKey, RowData)> = vec![];
+    /// Stream rows matching `request`, yielding each row as soon as its `CommitRow` chunk
+    /// arrives instead of buffering the whole response into a `Vec`.
+    ///
+    /// This drives a single `ReadRows` attempt with no retrying (unlike [`BigTable::read_rows`]);
+    /// callers that want large scans without holding an entire table slice in memory (e.g.
+    /// `traverse_directory`-style consumers) should use this directly and handle their own
+    /// resumption on error.
+    pub fn read_rows_stream(
+        &mut self,
+        request: ReadRowsRequest,
+    ) -> impl Stream<Item = Result<(RowKey, RowData)>> + '_ {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        self.read_rows_stream_with_deadline(request, deadline)
+    }
 
-        let mut row_key = None;
-        let mut row_data = vec![];
+    /// The actual implementation behind [`BigTable::read_rows_stream`], taking `deadline` as a
+    /// parameter instead of deriving it from `self.timeout` so that [`BigTable::read_rows`] can
+    /// compute it once up front and reuse the same deadline across every retry attempt, rather
+    /// than each attempt getting a fresh `self.timeout` from the moment it started.
+    fn read_rows_stream_with_deadline(
+        &mut self,
+        request: ReadRowsRequest,
+        deadline: Option<Instant>,
+    ) -> impl Stream<Item = Result<(RowKey, RowData)>> + '_ {
+        try_stream! {
+            let tagged_reads = self.compression_policy.tagged_reads;
+            let mut rrr = self.client.read_rows(request).await?.into_inner();
+            let mut decoder = RowDecoder::default();
 
-        let mut cell_name = None;
-        let mut cell_timestamp = 0;
-        let mut cell_value = vec![];
-        let mut cell_version_ok = true;
-        let started = Instant::now();
+            while let Some(res) = rrr.message().await? {
+                if let Some(deadline) = deadline {
+                    if Instant::now() > deadline {
+                        Err(Error::TimeoutError)?;
+                    }
+                }
+                for (i, chunk) in res.chunks.into_iter().enumerate() {
+                    // The comments for `read_rows_response::CellChunk` provide essential details
+                    // for understanding how the below decoding works...
+                    trace!("chunk {}: {:?}", i, chunk);
 
-        while let Some(res) = rrr.message().await? {
-            if let Some(timeout) = self.timeout {
-                if Instant::now().duration_since(started) > timeout {
-                    return Err(Error::TimeoutError);
+                    if let Some(row) = decoder.feed(chunk, tagged_reads)? {
+                        yield row;
+                    }
                 }
             }
-            for (i, mut chunk) in res.chunks.into_iter().enumerate() {
-                // The comments for `read_rows_response::CellChunk` provide essential details for
-                // understanding how the below decoding works...
-                trace!("chunk {}: {:?}", i, chunk);
-
-                // Starting a new row?
-                if !chunk.row_key.is_empty() {
-                    row_key = Some(chunk.row_key);
+        }
+    }
+
+    /// Write a single row to `table_name`, placing every cell in `row_data` under `family_name`.
+    ///
+    /// This issues a `MutateRow` RPC, which is atomic for the whole row but does not batch with
+    /// any other writes. Use [`BigTable::write_rows`] when writing many rows at once.
+    pub async fn write_row(
+        &mut self,
+        table_name: &str,
+        family_name: &str,
+        row_key: RowKey,
+        row_data: RowData,
+    ) -> Result<()> {
+        let mutations = row_data
+            .into_iter()
+            .map(|(cell_name, cell_value)| {
+                Ok(set_cell_mutation(
+                    family_name,
+                    cell_name,
+                    self.compress_cell_value(cell_value)?,
+                ))
+            })
+            .collect::<Result<_>>()?;
+
+        let request = MutateRowRequest {
+            table_name: format!("{}{}", self.table_prefix, table_name),
+            app_profile_id: String::new(),
+            row_key,
+            mutations,
+        };
+
+        self.client.mutate_row(request).await?;
+        Ok(())
+    }
+
+    /// Write a single row like [`BigTable::write_row`], but first split any cell value over
+    /// [`CELL_SIZE_LIMIT`] across sequentially-named qualifiers so BigTable doesn't reject it.
+    /// Returns `Error::RowWriteFailed` if a single cell's qualifier alone is long enough that not
+    /// even one chunk of it would fit under the limit, or if a qualifier already begins with the
+    /// reserved chunk-continuation marker byte (see [`chunk_cell_value`]). Plain [`BigTable::write_row`]
+    /// does not reject either case, so a qualifier it writes that begins with that marker byte
+    /// will be misread as a continuation by the read path.
+    pub async fn write_row_checked(
+        &mut self,
+        table_name: &str,
+        family_name: &str,
+        row_key: RowKey,
+        row_data: RowData,
+    ) -> Result<()> {
+        let mut chunked_row_data = Vec::with_capacity(row_data.len());
+        for (cell_name, cell_value) in row_data {
+            chunked_row_data.extend(chunk_cell_value(cell_name, cell_value)?);
+        }
+        self.write_row(table_name, family_name, row_key, chunked_row_data)
+            .await
+    }
+
+    /// Write many rows to `table_name` in a single `MutateRows` call, batching all of `rows`
+    /// into one streamed RPC rather than issuing one `MutateRow` per row.
+    ///
+    /// The server reports success or failure per row; any row that comes back with a non-OK
+    /// status is logged and causes this call to return [`Error::RowWriteFailed`], but all rows
+    /// are still attempted since `MutateRows` does not stop at the first failure.
+    pub async fn write_rows(
+        &mut self,
+        table_name: &str,
+        family_name: &str,
+        rows: Vec<(RowKey, RowData)>,
+    ) -> Result<()> {
+        let mut entries = Vec::with_capacity(rows.len());
+        for (row_key, row_data) in rows {
+            let mutations = row_data
+                .into_iter()
+                .map(|(cell_name, cell_value)| {
+                    Ok(set_cell_mutation(
+                        family_name,
+                        cell_name,
+                        self.compress_cell_value(cell_value)?,
+                    ))
+                })
+                .collect::<Result<_>>()?;
+            entries.push(Entry { row_key, mutations });
+        }
+
+        let request = MutateRowsRequest {
+            table_name: format!("{}{}", self.table_prefix, table_name),
+            app_profile_id: String::new(),
+            entries,
+        };
+
+        let mut stream = self.client.mutate_rows(request).await?.into_inner();
+        let mut any_failed = false;
+        while let Some(response) = stream.message().await? {
+            for entry in response.entries {
+                let code = entry.status.map(|status| status.code).unwrap_or(0);
+                if code != 0 {
+                    warn!("MutateRows: entry {} failed with status code {}", entry.index, code);
+                    any_failed = true;
                 }
+            }
+        }
 
-                // Starting a new cell?
-                if let Some(qualifier) = chunk.qualifier {
-                    if let Some(cell_name) = cell_name {
-                        row_data.push((cell_name, cell_value));
-                        cell_value = vec![];
-                    }
-                    cell_name = Some(qualifier);
-                    cell_timestamp = chunk.timestamp_micros;
-                    cell_version_ok = true;
+        if any_failed {
+            Err(Error::RowWriteFailed)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Write many rows like [`BigTable::write_rows`], but first split any cell value over
+    /// [`CELL_SIZE_LIMIT`] across sequentially-named qualifiers, the same way
+    /// [`BigTable::write_row_checked`] does for a single row.
+    pub async fn write_rows_checked(
+        &mut self,
+        table_name: &str,
+        family_name: &str,
+        rows: Vec<(RowKey, RowData)>,
+    ) -> Result<()> {
+        let mut chunked_rows = Vec::with_capacity(rows.len());
+        for (row_key, row_data) in rows {
+            let mut chunked_row_data = Vec::with_capacity(row_data.len());
+            for (cell_name, cell_value) in row_data {
+                chunked_row_data.extend(chunk_cell_value(cell_name, cell_value)?);
+            }
+            chunked_rows.push((row_key, chunked_row_data));
+        }
+        self.write_rows(table_name, family_name, chunked_rows).await
+    }
+
+    /// Compress `value` according to `self.compression_policy`, returning it tagged with
+    /// whichever method (possibly `CompressionMethod::None`) was used.
+    fn compress_cell_value(&self, value: CellValue) -> Result<CellValue> {
+        if !self.compression_policy.enabled || value.len() < self.compression_policy.threshold {
+            return compress(CompressionMethod::None, &value)
+                .map_err(|err| Error::ObjectCorrupt(err.to_string()));
+        }
+        match self.compression_policy.forced_method {
+            Some(method) => compress(method, &value),
+            None => compress_best(&value),
+        }
+        .map_err(|err| Error::ObjectCorrupt(err.to_string()))
+    }
+
+}
+
+/// Drives the `CellChunk` state machine described by `read_rows_response::CellChunk`'s own doc
+/// comments: accumulates chunks into cells and cells into a row, across as many chunks (and
+/// therefore as many `ReadRowsResponse` messages) as the row takes, only resetting once a
+/// `CommitRow` status completes it. Kept as its own type, independent of the live `try_stream!`
+/// in [`BigTable::read_rows_stream_with_deadline`], so the decoding logic is unit-testable
+/// without a real `ReadRows` RPC.
+struct RowDecoder {
+    row_key: Option<RowKey>,
+    row_data: RowData,
+    cell_name: Option<CellName>,
+    cell_timestamp: i64,
+    cell_value: CellValue,
+    cell_version_ok: bool,
+}
+
+impl Default for RowDecoder {
+    fn default() -> Self {
+        Self {
+            row_key: None,
+            row_data: vec![],
+            cell_name: None,
+            cell_timestamp: 0,
+            cell_value: vec![],
+            cell_version_ok: true,
+        }
+    }
+}
+
+impl RowDecoder {
+    /// Feed one cell chunk into the decoder. Returns the completed `(RowKey, RowData)` if
+    /// `chunk` carried a `CommitRow` status, resetting the decoder for the next row; otherwise
+    /// returns `None` and keeps accumulating.
+    fn feed(
+        &mut self,
+        mut chunk: CellChunk,
+        tagged_reads: bool,
+    ) -> Result<Option<(RowKey, RowData)>> {
+        // Starting a new row?
+        if !chunk.row_key.is_empty() {
+            self.row_key = Some(chunk.row_key);
+        }
+
+        // Starting a new cell?
+        if let Some(qualifier) = chunk.qualifier {
+            if let Some(cell_name) = self.cell_name.take() {
+                self.row_data.push((
+                    cell_name,
+                    decompress_cell_value(std::mem::take(&mut self.cell_value), tagged_reads)?,
+                ));
+            }
+            self.cell_name = Some(qualifier);
+            self.cell_timestamp = chunk.timestamp_micros;
+            self.cell_version_ok = true;
+        } else {
+            // Continuing the existing cell.  Check if this is the start of another version of the cell
+            if chunk.timestamp_micros != 0 {
+                if chunk.timestamp_micros < self.cell_timestamp {
+                    trace!("ignore older versions of the cell");
+                    self.cell_version_ok = false; // ignore older versions of the cell
                 } else {
-                    // Continuing the existing cell.  Check if this is the start of another version of the cell
-                    if chunk.timestamp_micros != 0 {
-                        if chunk.timestamp_micros < cell_timestamp {
-                            trace!("ignore older versions of the cell");
-                            cell_version_ok = false; // ignore older versions of the cell
-                        } else {
-                            // newer version of the cell, remove the older cell
-                            cell_version_ok = true;
-                            cell_value = vec![];
-                            cell_timestamp = chunk.timestamp_micros;
-                        }
-                    }
-                }
-                if cell_version_ok {
-                    cell_value.append(&mut chunk.value);
+                    // newer version of the cell, remove the older cell
+                    self.cell_version_ok = true;
+                    self.cell_value = vec![];
+                    self.cell_timestamp = chunk.timestamp_micros;
                 }
+            }
+        }
+        if self.cell_version_ok {
+            self.cell_value.append(&mut chunk.value);
+        }
 
-                // End of a row?
-                if let Some(RowStatus::CommitRow(_)) = chunk.row_status {
-                    if let Some(cell_name) = cell_name {
-                        row_data.push((cell_name, cell_value));
-                    }
+        // End of a row?
+        if let Some(RowStatus::CommitRow(_)) = chunk.row_status {
+            if let Some(cell_name) = self.cell_name.take() {
+                self.row_data.push((
+                    cell_name,
+                    decompress_cell_value(std::mem::take(&mut self.cell_value), tagged_reads)?,
+                ));
+            }
+            let row_data = reassemble_chunked_cells(std::mem::take(&mut self.row_data));
+            return Ok(self.row_key.take().map(|row_key| (row_key, row_data)));
+        }
 
-                    if let Some(row_key) = row_key {
-                        rows.push((row_key, row_data))
-                    }
-                }
+        Ok(None)
+    }
+}
+
+/// Is `err` worth retrying? Only RPC statuses that indicate a transient server- or
+/// network-level hiccup qualify; anything else (e.g. a malformed request) would fail again
+/// identically.
+fn is_retryable(err: &Error) -> bool {
+    matches!(
+        err,
+        Error::RpcError(status)
+            if matches!(
+                status.code(),
+                tonic::Code::Unavailable
+                    | tonic::Code::DeadlineExceeded
+                    | tonic::Code::ResourceExhausted
+                    | tonic::Code::Aborted
+                    | tonic::Code::Internal
+            )
+    )
+}
+
+/// Apply +/-50% jitter to `duration` so that many retrying clients don't all wake up and hammer
+/// the server at the same instant.
+fn jittered(duration: Duration) -> Duration {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos();
+    // subsec_nanos() ranges over [0, 1_000_000_000), so this spans the full [0.5, 1.5) factor
+    // range the doc above promises, instead of being skewed toward scaling the duration down.
+    let factor = 0.5 + (nanos as f64 / 1_000_000_000.0);
+    duration.mul_f64(factor)
+}
+
+fn next_backoff(current: Duration, policy: &RetryPolicy) -> Duration {
+    current.mul_f64(policy.multiplier).min(policy.max_backoff)
+}
+
+/// Narrow `request`'s row set so the retried scan starts immediately after `row_key`, since that
+/// row (and everything before it) has already been committed to the caller's result.
+///
+/// Only a `row_range` that actually reaches `row_key` is touched: one that ends at or before it
+/// is dropped entirely (already fully committed), and one that starts after it is left alone
+/// (the server hasn't returned anything from it yet). Narrowing every range unconditionally
+/// would, for disjoint ranges, turn an untouched later range into one that starts at `row_key`
+/// and so return rows outside the caller's original row set.
+///
+/// A request with no row keys or ranges (a whole-table scan) is narrowed to a single range open
+/// after `row_key`. Returns `None` when a bounded row set has nothing left after `row_key`.
+fn start_after_row_key(mut request: ReadRowsRequest, row_key: &RowKey) -> Option<ReadRowsRequest> {
+    let row_set = request.rows.get_or_insert_with(Default::default);
+    let unbounded = row_set.row_keys.is_empty() && row_set.row_ranges.is_empty();
+
+    row_set
+        .row_keys
+        .retain(|key| key.as_slice() > row_key.as_slice());
+
+    row_set.row_ranges.retain_mut(|range| {
+        let ends_at_or_before = match &range.end_key {
+            Some(row_range::EndKey::EndKeyClosed(end)) => end.as_slice() <= row_key.as_slice(),
+            Some(row_range::EndKey::EndKeyOpen(end)) => end.as_slice() <= row_key.as_slice(),
+            None => false,
+        };
+        if ends_at_or_before {
+            return false;
+        }
+
+        let starts_after = match &range.start_key {
+            Some(row_range::StartKey::StartKeyClosed(start)) => {
+                start.as_slice() > row_key.as_slice()
+            }
+            Some(row_range::StartKey::StartKeyOpen(start)) => {
+                start.as_slice() >= row_key.as_slice()
+            }
+            None => false,
+        };
+        if !starts_after {
+            range.start_key = Some(row_range::StartKey::StartKeyOpen(row_key.clone()));
+        }
+        true
+    });
+
+    if row_set.row_ranges.is_empty() && row_set.row_keys.is_empty() {
+        if !unbounded {
+            return None;
+        }
+        row_set.row_ranges.push(RowRange {
+            start_key: Some(row_range::StartKey::StartKeyOpen(row_key.clone())),
+            end_key: None,
+        });
+    }
+    Some(request)
+}
+
+#[cfg(test)]
+mod decode_tests {
+    use super::*;
+    use crate::google::bigtable::v2::read_rows_response::cell_chunk;
+
+    fn chunk(
+        row_key: &[u8],
+        qualifier: Option<&[u8]>,
+        value: &[u8],
+        commit_row: bool,
+    ) -> CellChunk {
+        CellChunk {
+            row_key: row_key.to_vec(),
+            qualifier: qualifier.map(|q| q.to_vec()),
+            value: value.to_vec(),
+            row_status: if commit_row {
+                Some(cell_chunk::RowStatus::CommitRow(true))
+            } else {
+                None
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn single_chunk_row_completes_immediately() {
+        let mut decoder = RowDecoder::default();
+        let row = decoder
+            .feed(chunk(b"row1", Some(b"col"), b"value", true), false)
+            .unwrap();
+        assert_eq!(
+            row,
+            Some((b"row1".to_vec(), vec![(b"col".to_vec(), b"value".to_vec())]))
+        );
+    }
+
+    #[test]
+    fn row_spanning_multiple_chunks_is_not_lost() {
+        // The row key and first cell arrive in one chunk, a second cell's value is split across
+        // two more chunks, and only the last of those carries CommitRow. The decoder must hold
+        // onto the row key and accumulated data across all three `feed` calls.
+        let mut decoder = RowDecoder::default();
+
+        assert_eq!(
+            decoder
+                .feed(chunk(b"row1", Some(b"col_a"), b"a", false), false)
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            decoder
+                .feed(chunk(b"", Some(b"col_b"), b"part1", false), false)
+                .unwrap(),
+            None
+        );
+        let row = decoder
+            .feed(chunk(b"", None, b"part2", true), false)
+            .unwrap();
+
+        assert_eq!(
+            row,
+            Some((
+                b"row1".to_vec(),
+                vec![
+                    (b"col_a".to_vec(), b"a".to_vec()),
+                    (b"col_b".to_vec(), b"part1part2".to_vec()),
+                ]
+            ))
+        );
+    }
+
+    #[test]
+    fn decoder_resets_after_yielding_a_row() {
+        let mut decoder = RowDecoder::default();
+        decoder
+            .feed(chunk(b"row1", Some(b"col"), b"value", true), false)
+            .unwrap();
+
+        let row = decoder
+            .feed(chunk(b"row2", Some(b"col"), b"other", true), false)
+            .unwrap();
+        assert_eq!(
+            row,
+            Some((b"row2".to_vec(), vec![(b"col".to_vec(), b"other".to_vec())]))
+        );
+    }
+}
+
+#[cfg(test)]
+mod retry_tests {
+    use super::*;
+    use crate::google::bigtable::v2::RowSet;
+
+    fn range(start: &[u8], end: &[u8]) -> RowRange {
+        RowRange {
+            start_key: Some(row_range::StartKey::StartKeyClosed(start.to_vec())),
+            end_key: Some(row_range::EndKey::EndKeyOpen(end.to_vec())),
+        }
+    }
+
+    #[test]
+    fn start_after_row_key_only_narrows_the_range_containing_it() {
+        let request = ReadRowsRequest {
+            rows: Some(RowSet {
+                row_keys: vec![],
+                row_ranges: vec![range(b"a", b"c"), range(b"x", b"z")],
+            }),
+            ..Default::default()
+        };
+
+        let narrowed = start_after_row_key(request, &b"b".to_vec()).unwrap();
+        let row_ranges = narrowed.rows.unwrap().row_ranges;
 
-                row_key = None;
-                row_data = vec![];
-                cell_value = vec![];
-                cell_name = None;
+        assert_eq!(row_ranges.len(), 2);
+        assert_eq!(
+            row_ranges[0].start_key,
+            Some(row_range::StartKey::StartKeyOpen(b"b".to_vec()))
+        );
+        assert_eq!(
+            row_ranges[0].end_key,
+            Some(row_range::EndKey::EndKeyOpen(b"c".to_vec()))
+        );
+        // The second range is entirely after "b" and must be left untouched.
+        assert_eq!(
+            row_ranges[1].start_key,
+            Some(row_range::StartKey::StartKeyClosed(b"x".to_vec()))
+        );
+    }
+
+    #[test]
+    fn start_after_row_key_drops_fully_committed_ranges() {
+        let request = ReadRowsRequest {
+            rows: Some(RowSet {
+                row_keys: vec![],
+                row_ranges: vec![range(b"a", b"c"), range(b"x", b"z")],
+            }),
+            ..Default::default()
+        };
+
+        // "d" is past the end of the first range but before the second, so the first range
+        // should be dropped and the second left untouched.
+        let narrowed = start_after_row_key(request, &b"d".to_vec()).unwrap();
+        let row_ranges = narrowed.rows.unwrap().row_ranges;
+
+        assert_eq!(row_ranges.len(), 1);
+        assert_eq!(
+            row_ranges[0].start_key,
+            Some(row_range::StartKey::StartKeyClosed(b"x".to_vec()))
+        );
+    }
+}
+
+/// Inflate a value pulled off the wire, translating any codec failure into
+/// `Error::ObjectCorrupt` since a value that doesn't match its own method tag indicates the
+/// stored bytes were damaged or truncated.
+///
+/// When `tagged_reads` is `false`, the value is returned untouched: its leading byte cannot be
+/// trusted to be a method tag (see [`CompressionPolicy::tagged_reads`]), so attempting to decode
+/// it would risk truncating or corrupting a legacy, untagged value.
+fn decompress_cell_value(cell_value: CellValue, tagged_reads: bool) -> Result<CellValue> {
+    if !tagged_reads {
+        return Ok(cell_value);
+    }
+    decompress(&cell_value).map_err(|err| Error::ObjectCorrupt(err.to_string()))
+}
+
+/// Split `cell_value` into `(CellName, CellValue)` chunks no larger than [`CELL_SIZE_LIMIT`]
+/// permits. If the value fits in one chunk, `cell_name` is used as-is; otherwise every chunk
+/// (including the first) is named `[CHUNK_MARKER] ++ cell_name.len() ++ cell_name ++ index`
+/// (see [`CHUNK_QUALIFIER_OVERHEAD`]), so `piece(0) < piece(1) < piece(2) ...` and a scan returns
+/// them in write order, ready for [`reassemble_chunked_cells`]. Fails with `Error::RowWriteFailed`
+/// if `cell_name` itself already begins with the reserved marker byte.
+fn chunk_cell_value(cell_name: CellName, cell_value: CellValue) -> Result<Vec<(CellName, CellValue)>> {
+    if cell_name.first() == Some(&CHUNK_MARKER) {
+        return Err(Error::RowWriteFailed);
+    }
+
+    let max_chunk_len =
+        CELL_SIZE_LIMIT.saturating_sub(cell_name.len() + CHUNK_QUALIFIER_OVERHEAD + CHUNK_OVERHEAD);
+    if max_chunk_len == 0 {
+        return Err(Error::RowWriteFailed);
+    }
+    if cell_value.len() <= max_chunk_len {
+        return Ok(vec![(cell_name, cell_value)]);
+    }
+
+    Ok(cell_value
+        .chunks(max_chunk_len)
+        .enumerate()
+        .map(|(i, piece)| {
+            let mut name = vec![CHUNK_MARKER];
+            name.extend_from_slice(&(cell_name.len() as u32).to_be_bytes());
+            name.extend_from_slice(&cell_name);
+            name.extend_from_slice(&(i as u32).to_be_bytes());
+            (name, piece.to_vec())
+        })
+        .collect())
+}
+
+/// The inverse of [`chunk_cell_value`]: merge adjacent cells that share a chunked base qualifier
+/// back into one logical `(CellName, CellValue)`. Relies on cells within a row arriving in
+/// qualifier-sorted order, which keeps a value's chunks contiguous and in sequence.
+fn reassemble_chunked_cells(row_data: RowData) -> RowData {
+    let mut merged: RowData = Vec::with_capacity(row_data.len());
+    for (cell_name, cell_value) in row_data {
+        if let Some(base_name) = chunk_base_name(&cell_name) {
+            if let Some(last) = merged.last_mut().filter(|(name, _)| *name == base_name) {
+                last.1.extend(cell_value);
+                continue;
             }
+            merged.push((base_name, cell_value));
+        } else {
+            merged.push((cell_name, cell_value));
         }
-        Ok(rows)
     }
+    merged
+}
+
+/// If `cell_name` is a [`CHUNK_MARKER`]-prefixed, length-prefixed chunk qualifier produced by
+/// [`chunk_cell_value`], return its base qualifier. Since `chunk_cell_value` refuses to write any
+/// ordinary qualifier starting with the marker byte, and the embedded length makes the base
+/// qualifier and trailing index unambiguous to parse out (no guessing where one ends and the
+/// other begins), finding it here reliably means this cell is a chunk piece it produced, not a
+/// coincidentally similar-looking qualifier someone else wrote.
+fn chunk_base_name(cell_name: &CellName) -> Option<CellName> {
+    let (&marker, rest) = cell_name.split_first()?;
+    if marker != CHUNK_MARKER {
+        return None;
+    }
+    if rest.len() < 4 {
+        return None;
+    }
+    let (len_bytes, rest) = rest.split_at(4);
+    let name_len = u32::from_be_bytes(len_bytes.try_into().ok()?) as usize;
+    if rest.len() != name_len + 4 {
+        return None;
+    }
+    Some(rest[..name_len].to_vec())
+}
+
+#[cfg(test)]
+mod chunk_tests {
+    use super::*;
+
+    #[test]
+    fn small_value_is_not_chunked() {
+        let chunks = chunk_cell_value(b"col".to_vec(), b"small value".to_vec()).unwrap();
+        assert_eq!(chunks, vec![(b"col".to_vec(), b"small value".to_vec())]);
+        assert_eq!(reassemble_chunked_cells(chunks.clone()), chunks);
+    }
+
+    #[test]
+    fn large_value_round_trips_through_chunk_and_reassemble() {
+        let cell_name = b"col".to_vec();
+        let value: Vec<u8> = (0..3).flat_map(|_| vec![0u8; CELL_SIZE_LIMIT / 2]).collect();
+
+        let mut chunks = chunk_cell_value(cell_name.clone(), value.clone()).unwrap();
+        assert!(chunks.len() > 1, "value should have been split into multiple chunks");
+
+        // BigTable returns cells in qualifier-sorted order, not write order -- re-sort before
+        // reassembling so this test exercises the order `reassemble_chunked_cells` actually sees.
+        chunks.sort_by(|a, b| a.0.cmp(&b.0));
+
+        let reassembled = reassemble_chunked_cells(chunks);
+        assert_eq!(reassembled, vec![(cell_name, value)]);
+    }
+
+    #[test]
+    fn chunk_qualifiers_sort_in_write_order() {
+        let cell_name = b"col".to_vec();
+        let value: Vec<u8> = (0..3).flat_map(|_| vec![0u8; CELL_SIZE_LIMIT / 2]).collect();
+
+        let chunks = chunk_cell_value(cell_name, value).unwrap();
+        let mut sorted = chunks.clone();
+        sorted.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(chunks, sorted, "chunk qualifiers must already be in ascending sort order");
+    }
+
+    #[test]
+    fn qualifier_that_looks_like_a_continuation_is_left_alone() {
+        // An ordinary qualifier that doesn't begin with CHUNK_MARKER is never mistaken for a
+        // chunk piece, however it's shaped -- even one laid out exactly like a chunk qualifier
+        // minus the leading marker byte.
+        let mut look_alike = 3u32.to_be_bytes().to_vec();
+        look_alike.extend_from_slice(b"col");
+        look_alike.extend_from_slice(&1u32.to_be_bytes());
+        assert_eq!(chunk_base_name(&look_alike), None);
+
+        let row_data = vec![
+            (b"a".to_vec(), b"first".to_vec()),
+            (look_alike.clone(), b"second".to_vec()),
+        ];
+        assert_eq!(reassemble_chunked_cells(row_data.clone()), row_data);
+    }
+
+    #[test]
+    fn chunk_base_name_rejects_a_malformed_marker_prefixed_qualifier() {
+        // Starts with CHUNK_MARKER but the embedded length doesn't match the remaining bytes --
+        // must be left alone rather than guessed at.
+        let mut malformed = vec![CHUNK_MARKER];
+        malformed.extend_from_slice(&42u32.to_be_bytes());
+        malformed.extend_from_slice(b"short");
+        assert_eq!(chunk_base_name(&malformed), None);
+    }
+
+    #[test]
+    fn chunk_cell_value_rejects_a_qualifier_that_already_uses_the_marker_byte() {
+        let mut reserved = vec![CHUNK_MARKER];
+        reserved.extend(b"col");
+        assert!(chunk_cell_value(reserved, b"value".to_vec()).is_err());
+    }
+}
+
+/// Build a `SetCell` mutation for `cell_name`/`cell_value` within `family_name`, timestamped
+/// "now" (server-assigned timestamps are not used since we want writes to be immediately
+/// readable at a known timestamp).
+fn set_cell_mutation(family_name: &str, cell_name: CellName, cell_value: CellValue) -> Mutation {
+    Mutation {
+        mutation: Some(MutationKind::SetCell(SetCell {
+            family_name: family_name.to_string(),
+            column_qualifier: cell_name,
+            timestamp_micros: now_micros(),
+            value: cell_value,
+        })),
+    }
+}
+
+/// Microseconds since the Unix epoch, rounded down to the millisecond since most BigTable
+/// tables are configured with millisecond timestamp granularity and reject anything finer.
+fn now_micros() -> i64 {
+    let micros = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as i64;
+    micros - (micros % 1_000)
 }
@@ -0,0 +1,140 @@
+//! Transparent compression for values stored in and read from BigTable cells.
+//!
+//! Mirrors the approach used by Solana's `storage-bigtable`: each stored value is prefixed with
+//! a single method-tag byte so that a value compressed with one codec can always be decompressed
+//! without the reader needing to know in advance which codec was used.
+
+use std::io::{Read, Write};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CompressionError {
+    #[error("unsupported compression method: {0}")]
+    UnsupportedCompressionMethod(u8),
+
+    #[error("compression error: {0}")]
+    CompressionError(#[from] std::io::Error),
+}
+
+pub type CompressionResult<T> = std::result::Result<T, CompressionError>;
+
+/// One-byte tag prepended to every stored value identifying which codec (if any) was used.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CompressionMethod {
+    None,
+    Bzip2,
+    Gzip,
+    Zstd,
+}
+
+impl CompressionMethod {
+    fn tag(self) -> u8 {
+        match self {
+            CompressionMethod::None => 0,
+            CompressionMethod::Bzip2 => 1,
+            CompressionMethod::Gzip => 2,
+            CompressionMethod::Zstd => 3,
+        }
+    }
+
+    fn from_tag(tag: u8) -> CompressionResult<Self> {
+        match tag {
+            0 => Ok(CompressionMethod::None),
+            1 => Ok(CompressionMethod::Bzip2),
+            2 => Ok(CompressionMethod::Gzip),
+            3 => Ok(CompressionMethod::Zstd),
+            _ => Err(CompressionError::UnsupportedCompressionMethod(tag)),
+        }
+    }
+}
+
+/// Compress `data` with `method`, returning the method tag byte followed by the compressed
+/// bytes. `CompressionMethod::None` just prepends the tag to an untouched copy of `data`.
+pub fn compress(method: CompressionMethod, data: &[u8]) -> CompressionResult<Vec<u8>> {
+    let mut compressed = vec![method.tag()];
+    match method {
+        CompressionMethod::None => compressed.extend_from_slice(data),
+        CompressionMethod::Bzip2 => {
+            let mut encoder = bzip2::write::BzEncoder::new(&mut compressed, bzip2::Compression::best());
+            encoder.write_all(data)?;
+            encoder.finish()?;
+        }
+        CompressionMethod::Gzip => {
+            let mut encoder = flate2::write::GzEncoder::new(&mut compressed, flate2::Compression::best());
+            encoder.write_all(data)?;
+            encoder.finish()?;
+        }
+        CompressionMethod::Zstd => {
+            compressed = vec![method.tag()];
+            compressed.extend(zstd::stream::encode_all(data, 0)?);
+        }
+    }
+    Ok(compressed)
+}
+
+/// Try every supported codec and keep whichever produces the smallest output, falling back to
+/// `CompressionMethod::None` if nothing beats storing the value as-is.
+pub fn compress_best(data: &[u8]) -> CompressionResult<Vec<u8>> {
+    let mut best = compress(CompressionMethod::None, data)?;
+    for method in [
+        CompressionMethod::Bzip2,
+        CompressionMethod::Gzip,
+        CompressionMethod::Zstd,
+    ] {
+        let candidate = compress(method, data)?;
+        if candidate.len() < best.len() {
+            best = candidate;
+        }
+    }
+    Ok(best)
+}
+
+/// Inflate a value previously produced by [`compress`] or [`compress_best`], dispatching on the
+/// leading method-tag byte.
+pub fn decompress(data: &[u8]) -> CompressionResult<Vec<u8>> {
+    let (&tag, data) = data
+        .split_first()
+        .ok_or(CompressionError::UnsupportedCompressionMethod(0))?;
+    match CompressionMethod::from_tag(tag)? {
+        CompressionMethod::None => Ok(data.to_vec()),
+        CompressionMethod::Bzip2 => {
+            let mut decoder = bzip2::read::BzDecoder::new(data);
+            let mut out = vec![];
+            decoder.read_to_end(&mut out)?;
+            Ok(out)
+        }
+        CompressionMethod::Gzip => {
+            let mut decoder = flate2::read::GzDecoder::new(data);
+            let mut out = vec![];
+            decoder.read_to_end(&mut out)?;
+            Ok(out)
+        }
+        CompressionMethod::Zstd => Ok(zstd::stream::decode_all(data)?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip_best() {
+        let data = b"the quick brown fox jumps over the lazy dog".repeat(64);
+        let compressed = compress_best(&data).unwrap();
+        assert_eq!(decompress(&compressed).unwrap(), data);
+    }
+
+    #[test]
+    fn roundtrip_each_method() {
+        let data = b"some arbitrary cell value";
+        for method in [
+            CompressionMethod::None,
+            CompressionMethod::Bzip2,
+            CompressionMethod::Gzip,
+            CompressionMethod::Zstd,
+        ] {
+            let compressed = compress(method, data).unwrap();
+            assert_eq!(decompress(&compressed).unwrap(), data);
+        }
+    }
+}